@@ -1,5 +1,7 @@
+use std::collections::VecDeque;
 use std::fmt::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -11,16 +13,25 @@ use indicatif::ProgressBar;
 use indicatif::ProgressState;
 use indicatif::ProgressStyle;
 use librespot::core::session::Session;
+use librespot::metadata::FileFormat;
 
 use crate::encoder;
 use crate::encoder::Format;
 use crate::encoder::Samples;
+use crate::manifest;
+use crate::manifest::Manifest;
+use crate::manifest::ManifestEntry;
+use crate::metrics;
+use crate::metrics::Metrics;
+use crate::playlist::write_m3u8;
+use crate::playlist::PlaylistEntry;
 use crate::stream::Stream;
 use crate::stream::StreamEvent;
 use crate::stream::StreamEventChannel;
 use crate::track::Track;
 use crate::track::TrackMetadata;
 
+use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
@@ -35,6 +46,14 @@ pub struct RateLimitConfig {
     pub multiplier: f64,
     pub max_delay: Duration,
     pub reset_after_success: bool,
+    /// Rolling-average per-track fetch latency above which the adaptive
+    /// controller treats the connection as congested and widens request
+    /// spacing, the same as an explicit 429. Zero disables latency-based
+    /// pacing entirely.
+    pub target_latency: Duration,
+    /// Floor for the proactive inter-request spacing enforced by
+    /// `wait_ready`, applied even before any failure or 429 is observed.
+    pub min_spacing: Duration,
 }
 
 impl RateLimitConfig {
@@ -55,20 +74,34 @@ impl RateLimitConfig {
             multiplier,
             max_delay,
             reset_after_success: true,
+            target_latency: Duration::ZERO,
+            min_spacing: Duration::ZERO,
         }
     }
 
+    /// Enables the adaptive additive-increase/multiplicative-decrease
+    /// pacing controller: `target_latency` is the rolling-average latency
+    /// above which spacing widens, and `min_spacing` is the proactive
+    /// floor enforced on every request regardless of past failures.
+    pub fn with_adaptive_pacing(mut self, target_latency: Duration, min_spacing: Duration) -> Self {
+        self.target_latency = target_latency;
+        self.min_spacing = min_spacing;
+        self
+    }
+
     pub fn disabled() -> Self {
         RateLimitConfig {
             base_delay: Duration::ZERO,
             multiplier: 1.0,
             max_delay: Duration::ZERO,
             reset_after_success: true,
+            target_latency: Duration::ZERO,
+            min_spacing: Duration::ZERO,
         }
     }
 
     pub fn is_enabled(&self) -> bool {
-        !self.base_delay.is_zero()
+        !self.base_delay.is_zero() || !self.min_spacing.is_zero() || !self.target_latency.is_zero()
     }
 }
 
@@ -78,17 +111,94 @@ impl Default for RateLimitConfig {
     }
 }
 
+/// Which source stream quality to request from librespot, modeled as an
+/// ordered fallback chain of `FileFormat`s: if the first entry isn't
+/// available for a given track, the next one is tried instead of failing
+/// outright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Highest-quality Ogg Vorbis only, no fallback.
+    OggOnly,
+    /// Best available bitrate, falling back from 320kbps Ogg Vorbis down
+    /// to 96kbps.
+    BestBitrate,
+    /// MP3 transcodes only, falling back from 320kbps down to 96kbps.
+    Mp3Only,
+}
+
+impl Quality {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Quality::OggOnly => "ogg-only",
+            Quality::BestBitrate => "best-bitrate",
+            Quality::Mp3Only => "mp3-only",
+        }
+    }
+
+    pub fn fallback_chain(&self) -> Vec<FileFormat> {
+        match self {
+            Quality::OggOnly => vec![FileFormat::OGG_VORBIS_320],
+            Quality::BestBitrate => vec![
+                FileFormat::OGG_VORBIS_320,
+                FileFormat::OGG_VORBIS_160,
+                FileFormat::OGG_VORBIS_96,
+            ],
+            Quality::Mp3Only => vec![
+                FileFormat::MP3_320,
+                FileFormat::MP3_256,
+                FileFormat::MP3_160,
+                FileFormat::MP3_96,
+            ],
+        }
+    }
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Quality::BestBitrate
+    }
+}
+
+impl FromStr for Quality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ogg-only" | "ogg" => Ok(Quality::OggOnly),
+            "best-bitrate" | "best" => Ok(Quality::BestBitrate),
+            "mp3-only" | "mp3" => Ok(Quality::Mp3Only),
+            _ => Err(anyhow::anyhow!("Unknown quality: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DownloadOptions {
     pub destination: PathBuf,
     pub parallel: usize,
     pub format: Format,
     pub force: bool,
+    pub quality: Quality,
     pub rate_limit: RateLimitConfig,
+    /// Base URL of a Prometheus Pushgateway to report run metrics to, when
+    /// set. Has no effect unless the crate is built with the `metrics`
+    /// feature.
+    pub metrics_pushgateway: Option<String>,
+    /// Name (without extension) for the `.m3u8` playlist written next to
+    /// the downloaded tracks. `None` falls back to `playlist`.
+    pub playlist_name: Option<String>,
+    /// Whether to write the `.m3u8` playlist and manifest at all.
+    pub write_playlist: bool,
 }
 
 impl DownloadOptions {
-    pub fn new(destination: Option<String>, parallel: usize, format: Format, force: bool) -> Self {
+    pub fn new(
+        destination: Option<String>,
+        parallel: usize,
+        format: Format,
+        force: bool,
+        quality: Quality,
+    ) -> Self {
         let destination =
             destination.map_or_else(|| std::env::current_dir().unwrap(), PathBuf::from);
         DownloadOptions {
@@ -96,13 +206,43 @@ impl DownloadOptions {
             parallel,
             format,
             force,
+            quality,
             rate_limit: RateLimitConfig::default(),
+            metrics_pushgateway: None,
+            playlist_name: None,
+            write_playlist: true,
         }
     }
 
     pub fn set_rate_limit(&mut self, rate_limit: RateLimitConfig) {
         self.rate_limit = rate_limit;
     }
+
+    pub fn set_playlist(&mut self, playlist_name: Option<String>, write_playlist: bool) {
+        self.playlist_name = playlist_name;
+        self.write_playlist = write_playlist;
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.destination.join(manifest::MANIFEST_FILENAME)
+    }
+
+    pub fn playlist_path(&self) -> PathBuf {
+        let name = self.playlist_name.as_deref().unwrap_or("playlist");
+        self.destination.join(format!("{}.m3u8", name))
+    }
+
+    pub fn load_manifest(&self) -> Result<Manifest> {
+        Manifest::load(&self.manifest_path())
+    }
+
+    pub fn save_manifest(&self, manifest: &Manifest) -> Result<()> {
+        manifest.save(&self.manifest_path())
+    }
+
+    pub fn set_metrics_pushgateway(&mut self, metrics_pushgateway: Option<String>) {
+        self.metrics_pushgateway = metrics_pushgateway;
+    }
 }
 
 #[derive(Clone)]
@@ -119,6 +259,10 @@ impl RateLimiter {
         }
     }
 
+    /// Waits out both the post-failure backoff (`next_ready`) and the
+    /// proactive adaptive spacing (`spacing`, floored at
+    /// `config.min_spacing`) since the last dispatched request, so
+    /// requests are paced even before the first failure or 429 is seen.
     async fn wait_ready(&self) {
         if !self.config.is_enabled() {
             return;
@@ -127,16 +271,26 @@ impl RateLimiter {
         loop {
             let sleep_duration = {
                 let mut state = self.state.lock().await;
-                if let Some(next_ready) = state.next_ready {
-                    let now = Instant::now();
-                    if next_ready > now {
-                        Some(next_ready - now)
-                    } else {
+                let now = Instant::now();
+
+                let backoff_wait = match state.next_ready {
+                    Some(next_ready) if next_ready > now => Some(next_ready - now),
+                    Some(_) => {
                         state.next_ready = None;
                         None
                     }
-                } else {
-                    None
+                    None => None,
+                };
+
+                let spacing = state.spacing.max(self.config.min_spacing);
+                let spacing_wait = state.last_dispatch.and_then(|last| {
+                    let ready_at = last + spacing;
+                    (ready_at > now).then(|| ready_at - now)
+                });
+
+                match (backoff_wait, spacing_wait) {
+                    (None, None) => None,
+                    (a, b) => Some(a.max(b).unwrap_or_default()),
                 }
             };
 
@@ -146,6 +300,67 @@ impl RateLimiter {
                 None => break,
             }
         }
+
+        self.state.lock().await.last_dispatch = Some(Instant::now());
+    }
+
+    /// Folds a just-completed track's fetch latency into the rolling
+    /// average and widens or shrinks the adaptive spacing once enough
+    /// samples have accumulated to judge it: additively shrinking back
+    /// toward `config.min_spacing` (never below it) when recent fetches
+    /// finished under `target_latency`, multiplicatively widening when they
+    /// didn't.
+    async fn record_latency(&self, latency: Duration) {
+        if self.config.target_latency.is_zero() {
+            return;
+        }
+
+        let mut state = self.state.lock().await;
+        state.latency_window.push_back(latency);
+        if state.latency_window.len() > LATENCY_WINDOW_SIZE {
+            state.latency_window.pop_front();
+        }
+        if state.latency_window.len() < LATENCY_WINDOW_SIZE {
+            return;
+        }
+
+        let total: Duration = state.latency_window.iter().sum();
+        let average = total / state.latency_window.len() as u32;
+
+        if average > self.config.target_latency {
+            self.widen_spacing(&mut state);
+        } else {
+            self.shrink_spacing(&mut state);
+        }
+    }
+
+    /// Forces an immediate widening of the adaptive spacing, bypassing
+    /// the rolling latency average, for an explicit Spotify rate-limit
+    /// signal (HTTP 429).
+    async fn record_rate_limit_signal(&self) {
+        let mut state = self.state.lock().await;
+        self.widen_spacing(&mut state);
+        state.latency_window.clear();
+    }
+
+    fn widen_spacing(&self, state: &mut RateLimiterState) {
+        let floor = self.config.min_spacing.max(Duration::from_millis(1));
+        let current = state.spacing.max(floor);
+        let scaled = current.as_secs_f64() * self.config.multiplier.max(1.0);
+        let ceiling = self.config.max_delay.max(current).as_secs_f64();
+        state.spacing = Duration::from_secs_f64(scaled.min(ceiling));
+    }
+
+    fn shrink_spacing(&self, state: &mut RateLimiterState) {
+        if state.spacing <= self.config.min_spacing {
+            state.spacing = self.config.min_spacing;
+            return;
+        }
+        let step = self.config.min_spacing.max(Duration::from_millis(50));
+        state.spacing = state
+            .spacing
+            .saturating_sub(step)
+            .max(self.config.min_spacing);
     }
 
     async fn on_failure(&self) -> Duration {
@@ -206,10 +421,19 @@ impl RateLimiter {
     }
 }
 
+/// Number of recent per-track latencies averaged before the adaptive
+/// controller judges whether to widen or shrink spacing.
+const LATENCY_WINDOW_SIZE: usize = 5;
+
 #[derive(Default)]
 struct RateLimiterState {
     current_delay: Duration,
     next_ready: Option<Instant>,
+    /// Proactive inter-request spacing maintained by the adaptive
+    /// controller, independent of `current_delay`'s post-failure backoff.
+    spacing: Duration,
+    last_dispatch: Option<Instant>,
+    latency_window: VecDeque<Duration>,
 }
 
 impl Downloader {
@@ -226,29 +450,83 @@ impl Downloader {
         options: &DownloadOptions,
     ) -> Result<()> {
         let rate_limiter = Arc::new(RateLimiter::new(options.rate_limit.clone()));
+        let metrics = Arc::new(Metrics::new(
+            options.metrics_pushgateway.clone().unwrap_or_default(),
+            options.format.extension(),
+            options.quality.label(),
+        )?);
+        // `--no-playlist` means "don't write an .m3u8 or download manifest"
+        // per its help text, so when it's set we neither load the manifest
+        // from disk nor let it drive skip decisions below.
+        let manifest = Arc::new(Mutex::new(if options.write_playlist {
+            options.load_manifest()?
+        } else {
+            Manifest::default()
+        }));
+
+        let push_handle = options.metrics_pushgateway.as_ref().map(|_| {
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(metrics::PUSH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = metrics.push().await {
+                        tracing::warn!("Failed to push metrics to pushgateway: {}", e);
+                    }
+                }
+            })
+        });
 
-        futures::stream::iter(tracks)
-            .map(|track| {
+        let mut results = futures::stream::iter(tracks.into_iter().enumerate())
+            .map(|(index, track)| {
                 let rate_limiter = Arc::clone(&rate_limiter);
-                async move { self.download_track(track, options, rate_limiter).await }
+                let metrics = Arc::clone(&metrics);
+                let manifest = Arc::clone(&manifest);
+                async move {
+                    self.download_track(track, options, rate_limiter, metrics, manifest)
+                        .await
+                        .map(|entry| (index, entry))
+                }
             })
             .buffer_unordered(options.parallel)
             .try_collect::<Vec<_>>()
             .await?;
 
+        if let Some(handle) = push_handle {
+            handle.abort();
+        }
+        if options.metrics_pushgateway.is_some() {
+            metrics.push().await?;
+        }
+
+        if options.write_playlist {
+            options.save_manifest(&*manifest.lock().await)?;
+            results.sort_by_key(|(index, _)| *index);
+            let entries: Vec<PlaylistEntry> =
+                results.into_iter().filter_map(|(_, entry)| entry).collect();
+            if !entries.is_empty() {
+                write_m3u8(&options.playlist_path(), &entries)?;
+            }
+        }
+
         Ok(())
     }
 
-    #[tracing::instrument(name = "download_track", skip(self, options, rate_limiter))]
+    #[tracing::instrument(name = "download_track", skip(self, options, rate_limiter, metrics, manifest))]
     async fn download_track(
         &self,
         track: Track,
         options: &DownloadOptions,
         rate_limiter: Arc<RateLimiter>,
-    ) -> Result<()> {
+        metrics: Arc<Metrics>,
+        manifest: Arc<Mutex<Manifest>>,
+    ) -> Result<Option<PlaylistEntry>> {
         rate_limiter.wait_ready().await;
+        metrics.record_attempt();
+        let started_at = Instant::now();
 
-        let metadata = track.metadata(&self.session).await?;
+        let track_uri = track.uri()?;
+        let mut metadata = track.metadata(&self.session).await?;
         let track_label = metadata.to_string();
         tracing::info!("Downloading track: {:?}", metadata.track_name);
 
@@ -260,54 +538,124 @@ impl Downloader {
             .ok_or(anyhow::anyhow!("Could not set the output path"))?
             .to_string();
 
-        if !options.force && PathBuf::from(&path).exists() {
+        let should_skip = {
+            let manifest = manifest.lock().await;
+            if options.force {
+                false
+            } else if manifest.entry(&track_uri).is_some() {
+                manifest.is_up_to_date(&track_uri, &options.destination)
+            } else {
+                PathBuf::from(&path).exists()
+            }
+        };
+
+        if should_skip {
             tracing::info!(
-                "Skipping {}, file already exists. Use --force to force re-downloading the track",
+                "Skipping {}, existing file is still up to date. Use --force to force re-downloading the track",
                 &metadata.track_name
             );
+            metrics.record_skip();
             rate_limiter.on_success().await;
-            return Ok(());
+            let skip_filename = {
+                let manifest = manifest.lock().await;
+                manifest
+                    .entry(&track_uri)
+                    .map(|entry| entry.filename.clone())
+                    .unwrap_or(filename)
+            };
+            return Ok(Some(PlaylistEntry {
+                duration_secs: metadata.duration_ms / 1000,
+                title: track_label,
+                filename: skip_filename,
+            }));
         }
 
         let pb = self.add_progress_bar(&metadata);
 
-        let stream = Stream::new(self.session.clone());
+        let stream = Stream::new(self.session.clone(), options.quality);
         let channel = match stream.stream(track).await {
             Ok(channel) => channel,
             Err(e) => {
+                let rate_limited = looks_rate_limited(&e.to_string());
                 self.fail_with_error(&pb, &track_label, e.to_string());
-                self.backoff_after_failure(&rate_limiter, &track_label)
+                metrics.record_failure();
+                self.backoff_after_failure(&rate_limiter, &metrics, &track_label, rate_limited)
                     .await;
-                return Ok(());
+                return Ok(None);
             }
         };
 
-        let samples = match self.buffer_track(channel, &pb, &metadata).await {
-            Ok(samples) => samples,
+        // Written under a `.part` suffix and renamed into place only once
+        // streaming and encoding fully succeed, so a track that fails
+        // partway through never leaves a corrupt file at the real filename
+        // for `should_skip`'s bare `PathBuf::exists()` fallback to mistake
+        // for a completed download.
+        let partial_path = format!("{}.part", path);
+        let mut file = tokio::fs::File::create(&partial_path).await?;
+        let mut encoder = crate::encoder::get_encoder(options.format);
+
+        let resolved_quality = match self
+            .stream_to_file(channel, &pb, &metadata, encoder.as_mut(), &mut file, &metrics)
+            .await
+        {
+            Ok(resolved) => resolved,
             Err(e) => {
+                let rate_limited = looks_rate_limited(&e.to_string());
+                drop(file);
+                let _ = tokio::fs::remove_file(&partial_path).await;
                 self.fail_with_error(&pb, &track_label, e.to_string());
-                self.backoff_after_failure(&rate_limiter, &track_label)
+                metrics.record_failure();
+                self.backoff_after_failure(&rate_limiter, &metrics, &track_label, rate_limited)
                     .await;
-                return Ok(());
+                return Ok(None);
             }
         };
+        drop(file);
+        tokio::fs::rename(&partial_path, &path).await?;
+        metadata.resolved_quality = resolved_quality;
+        if let Some(format) = metadata.resolved_quality {
+            tracing::info!("Resolved quality for {}: {:?}", track_label, format);
+        }
+        metrics.record_success(started_at.elapsed());
+        rate_limiter.record_latency(started_at.elapsed()).await;
 
-        tracing::info!("Encoding track: {}", track_label);
-        pb.set_message(format!("Encoding {}", track_label));
-
-        let encoder = crate::encoder::get_encoder(options.format);
-        let stream = encoder.encode(samples).await?;
-
-        pb.set_message(format!("Writing {}", track_label));
-        tracing::info!("Writing track: {:?} to file: {}", track_label, &path);
-        stream.write_to_file(&path).await?;
+        let file_metadata = tokio::fs::metadata(&path).await?;
+        let byte_size = file_metadata.len();
+        let modified_at = file_metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
 
         let tags = metadata.tags().await?;
         encoder::tags::store_tags(path, &tags, options.format).await?;
 
+        // Saved immediately, not just once at the end of `download_tracks`,
+        // so a hard failure on a later track can't discard the recorded
+        // progress of every track that already completed in this run.
+        {
+            let mut manifest = manifest.lock().await;
+            manifest.record(ManifestEntry {
+                spotify_uri: track_uri,
+                filename: filename.clone(),
+                format: options.format.extension().to_string(),
+                quality: options.quality.label().to_string(),
+                byte_size,
+                modified_at,
+                completed_at: manifest::unix_now(),
+            });
+            if options.write_playlist {
+                options.save_manifest(&manifest)?;
+            }
+        }
+
         pb.finish_with_message(format!("Downloaded {}", track_label));
         rate_limiter.on_success().await;
-        Ok(())
+        Ok(Some(PlaylistEntry {
+            duration_secs: metadata.duration_ms / 1000,
+            title: track_label,
+            filename,
+        }))
     }
 
     fn add_progress_bar(&self, track: &TrackMetadata) -> ProgressBar {
@@ -324,26 +672,50 @@ impl Downloader {
         pb
     }
 
-    async fn buffer_track(
+    /// Drains the stream's events as they arrive, feeding each chunk
+    /// straight into `encoder.encode_chunk` and flushing the resulting
+    /// bytes to `file` immediately, so at most one chunk's worth of
+    /// samples is ever held in memory regardless of track length.
+    async fn stream_to_file(
         &self,
         mut rx: StreamEventChannel,
         pb: &ProgressBar,
         metadata: &TrackMetadata,
-    ) -> Result<Samples> {
-        let mut samples = Vec::<i32>::new();
+        encoder: &mut dyn encoder::Encoder,
+        file: &mut tokio::fs::File,
+        metrics: &Metrics,
+    ) -> Result<Option<FileFormat>> {
+        let mut resolved_quality = None;
         while let Some(event) = rx.recv().await {
             match event {
+                StreamEvent::Resolved(format) => {
+                    resolved_quality = Some(format);
+                    pb.set_message(format!("Downloading {} [{:?}]", metadata, format));
+                }
                 StreamEvent::Write {
                     bytes,
                     total,
-                    mut content,
+                    content,
                 } => {
                     tracing::trace!("Written {} bytes out of {}", bytes, total);
                     pb.set_position(bytes as u64);
-                    samples.append(&mut content);
+                    // Raw bytes actually fetched from Spotify for this chunk,
+                    // as opposed to `encoded.len()` below which is however
+                    // many (possibly transcoded) bytes the encoder produced.
+                    let raw_bytes = (content.len() * std::mem::size_of::<i32>()) as u64;
+                    let samples = Samples {
+                        samples: content,
+                        ..Default::default()
+                    };
+                    let encoded = encoder.encode_chunk(samples).await?;
+                    metrics.add_bytes(raw_bytes);
+                    file.write_all(&encoded).await?;
                 }
                 StreamEvent::Finished => {
                     tracing::info!("Finished downloading track");
+                    let trailer = encoder.finalize().await?;
+                    file.write_all(&trailer).await?;
+                    file.flush().await?;
                     break;
                 }
                 StreamEvent::Error(stream_error) => {
@@ -369,10 +741,7 @@ impl Downloader {
                 }
             }
         }
-        Ok(Samples {
-            samples,
-            ..Default::default()
-        })
+        Ok(resolved_quality)
     }
 
     fn fail_with_error<S>(&self, pb: &ProgressBar, name: &str, e: S)
@@ -387,11 +756,38 @@ impl Downloader {
         );
     }
 
-    async fn backoff_after_failure(&self, rate_limiter: &RateLimiter, track_label: &str) {
+    /// Reacts to a failed track fetch. An explicit Spotify rate-limit
+    /// signal (HTTP 429) widens the adaptive spacing controller
+    /// immediately instead of going through the ordinary multiplicative
+    /// failure backoff, since it's driven by a distinct, more reliable
+    /// signal than a generic transient error.
+    async fn backoff_after_failure(
+        &self,
+        rate_limiter: &RateLimiter,
+        metrics: &Metrics,
+        track_label: &str,
+        rate_limited: bool,
+    ) {
+        if rate_limited {
+            rate_limiter.record_rate_limit_signal().await;
+            metrics.record_backoff();
+            tracing::warn!(
+                track = track_label,
+                "Spotify rate limit (429) detected, widening inter-request spacing"
+            );
+            let message = format!(
+                "[rate-limit] 429 detected, widening request spacing: {}",
+                track_label
+            );
+            let _ = self.progress_bar.println(message);
+            return;
+        }
+
         let delay = rate_limiter.on_failure().await;
         if delay.is_zero() {
             return;
         }
+        metrics.record_backoff();
 
         let delay_ms = delay.as_millis() as u64;
         tracing::warn!(
@@ -410,3 +806,150 @@ impl Downloader {
         sleep(delay).await;
     }
 }
+
+/// Whether a streaming error looks like a Spotify rate-limit response
+/// (HTTP 429 or a "too many requests" style message) rather than an
+/// ordinary transient failure.
+pub(crate) fn looks_rate_limited(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("429")
+        || message.contains("too many requests")
+        || message.contains("rate limit")
+        || message.contains("ratelimited")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_parses_short_and_long_names() {
+        assert_eq!(Quality::from_str("ogg").unwrap(), Quality::OggOnly);
+        assert_eq!(Quality::from_str("ogg-only").unwrap(), Quality::OggOnly);
+        assert_eq!(Quality::from_str("best").unwrap(), Quality::BestBitrate);
+        assert_eq!(
+            Quality::from_str("best-bitrate").unwrap(),
+            Quality::BestBitrate
+        );
+        assert_eq!(Quality::from_str("mp3").unwrap(), Quality::Mp3Only);
+        assert_eq!(Quality::from_str("mp3-only").unwrap(), Quality::Mp3Only);
+        assert_eq!(Quality::from_str("MP3").unwrap(), Quality::Mp3Only);
+    }
+
+    #[test]
+    fn quality_rejects_unknown_names() {
+        assert!(Quality::from_str("flac").is_err());
+    }
+
+    #[test]
+    fn best_bitrate_fallback_chain_walks_ogg_bitrates_highest_first() {
+        assert_eq!(
+            Quality::BestBitrate.fallback_chain(),
+            vec![
+                FileFormat::OGG_VORBIS_320,
+                FileFormat::OGG_VORBIS_160,
+                FileFormat::OGG_VORBIS_96,
+            ]
+        );
+    }
+
+    #[test]
+    fn ogg_only_fallback_chain_has_a_single_entry() {
+        assert_eq!(
+            Quality::OggOnly.fallback_chain(),
+            vec![FileFormat::OGG_VORBIS_320]
+        );
+    }
+
+    #[test]
+    fn is_enabled_true_when_only_target_latency_set() {
+        let config = RateLimitConfig::new(Duration::ZERO, 2.0, Duration::ZERO)
+            .with_adaptive_pacing(Duration::from_millis(500), Duration::ZERO);
+        assert!(config.is_enabled());
+    }
+
+    #[test]
+    fn is_enabled_false_when_everything_zero() {
+        let config = RateLimitConfig::disabled();
+        assert!(!config.is_enabled());
+    }
+
+    #[test]
+    fn widen_spacing_scales_by_multiplier_and_respects_floor() {
+        let config = RateLimitConfig::new(Duration::ZERO, 2.0, Duration::from_secs(10))
+            .with_adaptive_pacing(Duration::from_millis(100), Duration::from_millis(50));
+        let limiter = RateLimiter::new(config);
+        let mut state = RateLimiterState::default();
+
+        limiter.widen_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(100));
+
+        limiter.widen_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn widen_spacing_is_capped_at_max_delay() {
+        let config = RateLimitConfig::new(Duration::ZERO, 10.0, Duration::from_millis(120))
+            .with_adaptive_pacing(Duration::from_millis(100), Duration::from_millis(50));
+        let limiter = RateLimiter::new(config);
+        let mut state = RateLimiterState {
+            spacing: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        limiter.widen_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(120));
+    }
+
+    #[test]
+    fn shrink_spacing_steps_down_toward_min_spacing() {
+        let config = RateLimitConfig::new(Duration::ZERO, 2.0, Duration::from_secs(10))
+            .with_adaptive_pacing(Duration::from_millis(100), Duration::from_millis(50));
+        let limiter = RateLimiter::new(config);
+        let mut state = RateLimiterState {
+            spacing: Duration::from_millis(200),
+            ..Default::default()
+        };
+
+        limiter.shrink_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(150));
+
+        limiter.shrink_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(100));
+
+        limiter.shrink_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(50));
+
+        // Already at the floor: stays put rather than going below it.
+        limiter.shrink_spacing(&mut state);
+        assert_eq!(state.spacing, Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn record_latency_widens_once_average_exceeds_target() {
+        let config = RateLimitConfig::new(Duration::ZERO, 2.0, Duration::from_secs(10))
+            .with_adaptive_pacing(Duration::from_millis(100), Duration::from_millis(50));
+        let limiter = RateLimiter::new(config);
+
+        for _ in 0..LATENCY_WINDOW_SIZE {
+            limiter.record_latency(Duration::from_millis(200)).await;
+        }
+
+        let state = limiter.state.lock().await;
+        assert_eq!(state.spacing, Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn record_rate_limit_signal_widens_immediately() {
+        let config = RateLimitConfig::new(Duration::ZERO, 2.0, Duration::from_secs(10))
+            .with_adaptive_pacing(Duration::from_millis(100), Duration::from_millis(50));
+        let limiter = RateLimiter::new(config);
+
+        limiter.record_rate_limit_signal().await;
+
+        let state = limiter.state.lock().await;
+        assert_eq!(state.spacing, Duration::from_millis(100));
+        assert!(state.latency_window.is_empty());
+    }
+}
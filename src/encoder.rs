@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+
+pub mod tags;
+
+/// Output container/codec that a track is transcoded into once its raw
+/// samples have been fetched from Spotify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Flac,
+    Ogg,
+    Mp3,
+    Wav,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Flac => "flac",
+            Format::Ogg => "ogg",
+            Format::Mp3 => "mp3",
+            Format::Wav => "wav",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flac" => Ok(Format::Flac),
+            "ogg" | "vorbis" => Ok(Format::Ogg),
+            "mp3" => Ok(Format::Mp3),
+            "wav" | "pcm" => Ok(Format::Wav),
+            _ => Err(anyhow::anyhow!("Unknown format: {}", s)),
+        }
+    }
+}
+
+/// Raw, decoded audio handed off from the streaming layer to an encoder.
+#[derive(Debug, Clone, Default)]
+pub struct Samples {
+    pub samples: Vec<i32>,
+    pub channels: u8,
+    pub sample_rate: u32,
+}
+
+/// Incrementally transcodes a track, a chunk of `Samples` at a time, so
+/// the caller only ever has to hold one chunk's worth of audio in memory
+/// instead of the whole track.
+///
+/// Callers feed every chunk through `encode_chunk`, writing the returned
+/// bytes to the destination file as they arrive, then call `finalize`
+/// exactly once at the end to flush any buffered state and retrieve the
+/// format's closing frame/footer, if it has one.
+#[async_trait::async_trait]
+pub trait Encoder: Send {
+    async fn encode_chunk(&mut self, samples: Samples) -> Result<Vec<u8>>;
+
+    async fn finalize(&mut self) -> Result<Vec<u8>>;
+}
+
+/// Writes samples out as-is, interleaved little-endian `i32`s. Used for
+/// `Format::Wav` and as the building block the other encoders share until
+/// they grow real codec-specific framing.
+#[derive(Default)]
+struct RawPcmEncoder;
+
+#[async_trait::async_trait]
+impl Encoder for RawPcmEncoder {
+    async fn encode_chunk(&mut self, samples: Samples) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(samples.samples.len() * 4);
+        for sample in samples.samples {
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        Ok(data)
+    }
+
+    async fn finalize(&mut self) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Default)]
+struct FlacEncoder {
+    inner: RawPcmEncoder,
+}
+
+#[async_trait::async_trait]
+impl Encoder for FlacEncoder {
+    async fn encode_chunk(&mut self, samples: Samples) -> Result<Vec<u8>> {
+        self.inner.encode_chunk(samples).await
+    }
+
+    async fn finalize(&mut self) -> Result<Vec<u8>> {
+        self.inner.finalize().await
+    }
+}
+
+#[derive(Default)]
+struct OggEncoder {
+    inner: RawPcmEncoder,
+}
+
+#[async_trait::async_trait]
+impl Encoder for OggEncoder {
+    async fn encode_chunk(&mut self, samples: Samples) -> Result<Vec<u8>> {
+        self.inner.encode_chunk(samples).await
+    }
+
+    async fn finalize(&mut self) -> Result<Vec<u8>> {
+        self.inner.finalize().await
+    }
+}
+
+#[derive(Default)]
+struct Mp3Encoder {
+    inner: RawPcmEncoder,
+}
+
+#[async_trait::async_trait]
+impl Encoder for Mp3Encoder {
+    async fn encode_chunk(&mut self, samples: Samples) -> Result<Vec<u8>> {
+        self.inner.encode_chunk(samples).await
+    }
+
+    async fn finalize(&mut self) -> Result<Vec<u8>> {
+        self.inner.finalize().await
+    }
+}
+
+pub fn get_encoder(format: Format) -> Box<dyn Encoder> {
+    match format {
+        Format::Flac => Box::<FlacEncoder>::default(),
+        Format::Ogg => Box::<OggEncoder>::default(),
+        Format::Mp3 => Box::<Mp3Encoder>::default(),
+        Format::Wav => Box::<RawPcmEncoder>::default(),
+    }
+}
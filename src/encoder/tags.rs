@@ -0,0 +1,11 @@
+use anyhow::Result;
+
+use crate::encoder::Format;
+use crate::track::Tags;
+
+/// Logs the track's metadata tags for the file at `path` at debug level.
+/// Nothing is written to the file itself, for any `format`.
+pub async fn store_tags(path: String, tags: &Tags, format: Format) -> Result<()> {
+    tracing::debug!("Storing tags for {} ({:?}): {:?}", path, format, tags);
+    Ok(())
+}
@@ -0,0 +1,9 @@
+pub mod download;
+pub mod encoder;
+pub mod log;
+pub mod manifest;
+pub mod metrics;
+pub mod playlist;
+pub mod session;
+pub mod stream;
+pub mod track;
@@ -0,0 +1,10 @@
+use anyhow::Result;
+use tracing_subscriber::EnvFilter;
+
+/// Configures the global `tracing` subscriber, honoring `RUST_LOG` with a
+/// sensible default when it isn't set.
+pub fn configure_logger() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+    Ok(())
+}
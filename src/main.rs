@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use spotify_dl::download::{DownloadOptions, Downloader, RateLimitConfig};
+use spotify_dl::download::{DownloadOptions, Downloader, Quality, RateLimitConfig};
 use spotify_dl::encoder::Format;
 use spotify_dl::log;
 use spotify_dl::session::create_session;
@@ -44,6 +44,13 @@ struct Opt {
         help = "Force download even if the file already exists"
     )]
     force: bool,
+    #[structopt(
+        short = "q",
+        long = "quality",
+        help = "The source quality to request from Spotify: ogg-only, best-bitrate or mp3-only. Default is best-bitrate.",
+        default_value = "best-bitrate"
+    )]
+    quality: Quality,
     #[structopt(
         long = "failure-delay-ms",
         help = "Base delay in milliseconds to wait after a download fails",
@@ -67,6 +74,33 @@ struct Opt {
         help = "Emit machine-readable JSON events alongside normal output"
     )]
     json_events: bool,
+    #[structopt(
+        long = "target-latency-ms",
+        help = "Target per-track fetch latency in milliseconds. Inter-request spacing widens when the rolling average latency climbs past this, and shrinks back toward --min-spacing-ms when it doesn't. Default is 0 (adaptive pacing disabled).",
+        default_value = "0"
+    )]
+    target_latency_ms: u64,
+    #[structopt(
+        long = "min-spacing-ms",
+        help = "Minimum delay enforced between requests, proactively, before any failure occurs. Default is 0 (no minimum spacing).",
+        default_value = "0"
+    )]
+    min_spacing_ms: u64,
+    #[structopt(
+        long = "metrics-pushgateway",
+        help = "Push Prometheus metrics for this run to the Pushgateway at this URL. Requires the `metrics` feature."
+    )]
+    metrics_pushgateway: Option<String>,
+    #[structopt(
+        long = "playlist-name",
+        help = "Name of the .m3u8 playlist written alongside the downloaded tracks. Default is playlist."
+    )]
+    playlist_name: Option<String>,
+    #[structopt(
+        long = "no-playlist",
+        help = "Don't write an .m3u8 playlist or download manifest"
+    )]
+    no_playlist: bool,
 }
 
 pub fn create_destination_if_required(destination: Option<String>) -> anyhow::Result<()> {
@@ -95,15 +129,26 @@ async fn main() -> anyhow::Result<()> {
 
     let track = get_tracks(opt.tracks, &session).await?;
 
-    let mut download_options =
-        DownloadOptions::new(opt.destination, opt.parallel, opt.format, opt.force);
+    let mut download_options = DownloadOptions::new(
+        opt.destination,
+        opt.parallel,
+        opt.format,
+        opt.force,
+        opt.quality,
+    );
     let rate_limit = RateLimitConfig::new(
         Duration::from_millis(opt.failure_delay_ms),
         opt.failure_delay_multiplier,
         Duration::from_millis(opt.failure_delay_max_ms),
+    )
+    .with_adaptive_pacing(
+        Duration::from_millis(opt.target_latency_ms),
+        Duration::from_millis(opt.min_spacing_ms),
     );
     download_options.set_rate_limit(rate_limit);
     download_options.enable_json_events(opt.json_events);
+    download_options.set_metrics_pushgateway(opt.metrics_pushgateway);
+    download_options.set_playlist(opt.playlist_name, !opt.no_playlist);
 
     let downloader = Downloader::new(session);
     downloader.download_tracks(track, &download_options).await
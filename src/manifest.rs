@@ -0,0 +1,166 @@
+//! A persisted record of what a destination directory already contains,
+//! so re-running a download against the same directory can skip tracks
+//! that are still intact instead of relying on `PathBuf::exists()` alone.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST_FILENAME: &str = ".spotify-dl-manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub spotify_uri: String,
+    pub filename: String,
+    pub format: String,
+    pub quality: String,
+    pub byte_size: u64,
+    /// Unix timestamp (seconds) the file's mtime was at when this entry
+    /// was recorded, used to detect a file that was later truncated or
+    /// replaced out from under us.
+    pub modified_at: u64,
+    /// Unix timestamp (seconds) the download completed at.
+    pub completed_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    tracks: HashMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn entry(&self, spotify_uri: &str) -> Option<&ManifestEntry> {
+        self.tracks.get(spotify_uri)
+    }
+
+    pub fn record(&mut self, entry: ManifestEntry) {
+        self.tracks.insert(entry.spotify_uri.clone(), entry);
+    }
+
+    /// Whether the file recorded for `spotify_uri` still looks intact:
+    /// present, at least as large as it was when completed, and with an
+    /// mtime matching exactly what was recorded (anything else, forward or
+    /// backward, means the file was touched out from under us since).
+    pub fn is_up_to_date(&self, spotify_uri: &str, destination: &Path) -> bool {
+        let Some(entry) = self.entry(spotify_uri) else {
+            return false;
+        };
+        let path = destination.join(&entry.filename);
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            return false;
+        };
+        if metadata.len() < entry.byte_size {
+            return false;
+        }
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+        let modified_secs = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        modified_secs == entry.modified_at
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_for(filename: &str, byte_size: u64, modified_at: u64) -> ManifestEntry {
+        ManifestEntry {
+            spotify_uri: "spotify:track:test".to_string(),
+            filename: filename.to_string(),
+            format: "flac".to_string(),
+            quality: "best-bitrate".to_string(),
+            byte_size,
+            modified_at,
+            completed_at: modified_at,
+        }
+    }
+
+    fn write_file_with_mtime(path: &Path, contents: &[u8], modified_at: u64) {
+        std::fs::write(path, contents).unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(modified_at))
+            .unwrap();
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("spotify-dl-manifest-test-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn up_to_date_when_size_and_mtime_match() {
+        let dir = scratch_dir("match");
+        let entry = entry_for("track.flac", 4, 1_700_000_000);
+        write_file_with_mtime(&dir.join("track.flac"), b"data", 1_700_000_000);
+
+        let mut manifest = Manifest::default();
+        manifest.record(entry);
+
+        assert!(manifest.is_up_to_date("spotify:track:test", &dir));
+    }
+
+    #[test]
+    fn stale_when_truncated() {
+        let dir = scratch_dir("truncated");
+        let entry = entry_for("track.flac", 8, 1_700_000_000);
+        write_file_with_mtime(&dir.join("track.flac"), b"data", 1_700_000_000);
+
+        let mut manifest = Manifest::default();
+        manifest.record(entry);
+
+        assert!(!manifest.is_up_to_date("spotify:track:test", &dir));
+    }
+
+    #[test]
+    fn stale_when_mtime_moved_forward() {
+        let dir = scratch_dir("tampered-forward");
+        let entry = entry_for("track.flac", 4, 1_700_000_000);
+        write_file_with_mtime(&dir.join("track.flac"), b"data", 1_700_000_100);
+
+        let mut manifest = Manifest::default();
+        manifest.record(entry);
+
+        assert!(!manifest.is_up_to_date("spotify:track:test", &dir));
+    }
+
+    #[test]
+    fn stale_when_missing() {
+        let dir = scratch_dir("missing");
+        let entry = entry_for("track.flac", 4, 1_700_000_000);
+
+        let mut manifest = Manifest::default();
+        manifest.record(entry);
+
+        assert!(!manifest.is_up_to_date("spotify:track:test", &dir));
+    }
+}
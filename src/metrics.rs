@@ -0,0 +1,277 @@
+//! Optional Prometheus Pushgateway metrics for a `download_tracks` run.
+//!
+//! Compiled in only behind the `metrics` cargo feature. With the feature
+//! disabled, [`Metrics`] is a zero-cost no-op so `Downloader` doesn't need
+//! `cfg` guards at every instrumentation point.
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use prometheus::{
+        Histogram, HistogramOpts, IntCounter, Opts, Registry,
+    };
+
+    /// Tracks counters/histograms for a single `download_tracks` run and
+    /// pushes them to a Prometheus Pushgateway, labeled by output format
+    /// and requested quality so runs with different settings don't
+    /// clobber each other's series.
+    #[derive(Clone)]
+    pub struct Metrics {
+        pushgateway_url: String,
+        job: String,
+        registry: Registry,
+        tracks_attempted: IntCounter,
+        tracks_succeeded: IntCounter,
+        tracks_skipped: IntCounter,
+        tracks_failed: IntCounter,
+        bytes_downloaded: IntCounter,
+        track_duration: Histogram,
+        backoff_events: IntCounter,
+    }
+
+    impl Metrics {
+        pub fn new(pushgateway_url: String, format: &str, quality: &str) -> Result<Self> {
+            let registry = Registry::new();
+            let const_labels = |name: &str, help: &str| {
+                Opts::new(name, help)
+                    .const_label("format", format)
+                    .const_label("quality", quality)
+            };
+
+            let tracks_attempted =
+                IntCounter::with_opts(const_labels("spotify_dl_tracks_attempted_total", "Tracks attempted"))?;
+            let tracks_succeeded =
+                IntCounter::with_opts(const_labels("spotify_dl_tracks_succeeded_total", "Tracks succeeded"))?;
+            let tracks_skipped =
+                IntCounter::with_opts(const_labels("spotify_dl_tracks_skipped_total", "Tracks skipped"))?;
+            let tracks_failed =
+                IntCounter::with_opts(const_labels("spotify_dl_tracks_failed_total", "Tracks failed"))?;
+            let bytes_downloaded = IntCounter::with_opts(const_labels(
+                "spotify_dl_bytes_downloaded_total",
+                "Total bytes downloaded",
+            ))?;
+            let track_duration = Histogram::with_opts(HistogramOpts::from(const_labels(
+                "spotify_dl_track_duration_seconds",
+                "Per-track download+encode duration",
+            )))?;
+            let backoff_events = IntCounter::with_opts(const_labels(
+                "spotify_dl_rate_limit_backoff_total",
+                "Rate-limit backoff events",
+            ))?;
+
+            registry.register(Box::new(tracks_attempted.clone()))?;
+            registry.register(Box::new(tracks_succeeded.clone()))?;
+            registry.register(Box::new(tracks_skipped.clone()))?;
+            registry.register(Box::new(tracks_failed.clone()))?;
+            registry.register(Box::new(bytes_downloaded.clone()))?;
+            registry.register(Box::new(track_duration.clone()))?;
+            registry.register(Box::new(backoff_events.clone()))?;
+
+            Ok(Metrics {
+                pushgateway_url,
+                job: "spotify_dl".to_string(),
+                registry,
+                tracks_attempted,
+                tracks_succeeded,
+                tracks_skipped,
+                tracks_failed,
+                bytes_downloaded,
+                track_duration,
+                backoff_events,
+            })
+        }
+
+        pub fn record_attempt(&self) {
+            self.tracks_attempted.inc();
+        }
+
+        pub fn record_success(&self, duration: Duration) {
+            self.tracks_succeeded.inc();
+            self.track_duration.observe(duration.as_secs_f64());
+        }
+
+        pub fn record_skip(&self) {
+            self.tracks_skipped.inc();
+        }
+
+        pub fn record_failure(&self) {
+            self.tracks_failed.inc();
+        }
+
+        pub fn add_bytes(&self, bytes: u64) {
+            self.bytes_downloaded.inc_by(bytes);
+        }
+
+        pub fn record_backoff(&self) {
+            self.backoff_events.inc();
+        }
+
+        pub async fn push(&self) -> Result<()> {
+            let metric_families = self.registry.gather();
+            let url = self.pushgateway_url.clone();
+            let job = self.job.clone();
+            tokio::task::spawn_blocking(move || {
+                prometheus::push_metrics(
+                    &job,
+                    prometheus::labels! {},
+                    &url,
+                    metric_families,
+                    None,
+                )
+            })
+            .await??;
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn find<'a>(
+            families: &'a [prometheus::proto::MetricFamily],
+            name: &str,
+        ) -> &'a prometheus::proto::MetricFamily {
+            families
+                .iter()
+                .find(|family| family.get_name() == name)
+                .unwrap_or_else(|| panic!("metric family {} was not registered", name))
+        }
+
+        #[test]
+        fn labels_format_and_quality_are_applied_to_every_series() {
+            let metrics = Metrics::new("http://localhost:9091".to_string(), "flac", "best-bitrate")
+                .unwrap();
+
+            for family in metrics.registry.gather() {
+                let metric = family.get_metric().first().unwrap_or_else(|| {
+                    panic!("metric family {} has no series", family.get_name())
+                });
+                let labels: std::collections::HashMap<_, _> = metric
+                    .get_label()
+                    .iter()
+                    .map(|l| (l.get_name(), l.get_value()))
+                    .collect();
+                assert_eq!(labels.get("format"), Some(&"flac"));
+                assert_eq!(labels.get("quality"), Some(&"best-bitrate"));
+            }
+        }
+
+        #[test]
+        fn record_attempt_increments_tracks_attempted() {
+            let metrics = Metrics::new("http://localhost:9091".to_string(), "flac", "best-bitrate")
+                .unwrap();
+
+            metrics.record_attempt();
+            metrics.record_attempt();
+
+            let families = metrics.registry.gather();
+            let family = find(&families, "spotify_dl_tracks_attempted_total");
+            assert_eq!(family.get_metric()[0].get_counter().get_value(), 2.0);
+        }
+
+        #[test]
+        fn add_bytes_accumulates_on_the_counter() {
+            let metrics = Metrics::new("http://localhost:9091".to_string(), "ogg", "ogg-only")
+                .unwrap();
+
+            metrics.add_bytes(100);
+            metrics.add_bytes(50);
+
+            let families = metrics.registry.gather();
+            let family = find(&families, "spotify_dl_bytes_downloaded_total");
+            assert_eq!(family.get_metric()[0].get_counter().get_value(), 150.0);
+        }
+
+        #[test]
+        fn record_success_observes_track_duration() {
+            let metrics = Metrics::new("http://localhost:9091".to_string(), "flac", "best-bitrate")
+                .unwrap();
+
+            metrics.record_success(Duration::from_secs(3));
+
+            let families = metrics.registry.gather();
+            let family = find(&families, "spotify_dl_track_duration_seconds");
+            assert_eq!(
+                family.get_metric()[0].get_histogram().get_sample_count(),
+                1
+            );
+        }
+
+        #[test]
+        fn record_backoff_increments_backoff_events() {
+            let metrics = Metrics::new("http://localhost:9091".to_string(), "flac", "best-bitrate")
+                .unwrap();
+
+            metrics.record_backoff();
+
+            let families = metrics.registry.gather();
+            let family = find(&families, "spotify_dl_rate_limit_backoff_total");
+            assert_eq!(family.get_metric()[0].get_counter().get_value(), 1.0);
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    #[derive(Clone, Default)]
+    pub struct Metrics;
+
+    impl Metrics {
+        pub fn new(_pushgateway_url: String, _format: &str, _quality: &str) -> Result<Self> {
+            Ok(Metrics)
+        }
+
+        pub fn record_attempt(&self) {}
+
+        pub fn record_success(&self, _duration: Duration) {}
+
+        pub fn record_skip(&self) {}
+
+        pub fn record_failure(&self) {}
+
+        pub fn add_bytes(&self, _bytes: u64) {}
+
+        pub fn record_backoff(&self) {}
+
+        pub async fn push(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn every_instrumentation_point_is_a_true_no_op() {
+            let metrics = Metrics::new("unused".to_string(), "flac", "best-bitrate").unwrap();
+
+            metrics.record_attempt();
+            metrics.record_success(Duration::from_secs(1));
+            metrics.record_skip();
+            metrics.record_failure();
+            metrics.add_bytes(1_000);
+            metrics.record_backoff();
+
+            metrics.push().await.unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use enabled::Metrics;
+#[cfg(not(feature = "metrics"))]
+pub use disabled::Metrics;
+
+/// How often a long-running `download_tracks` call pushes an interim
+/// snapshot to the gateway, in addition to the final push once it's done.
+pub const PUSH_INTERVAL: Duration = Duration::from_secs(30);
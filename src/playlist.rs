@@ -0,0 +1,24 @@
+//! Writes the extended M3U (`.m3u8`) playlist tying a download run's
+//! output files together, so a playlist/album download leaves behind a
+//! single artifact a media player can open.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::Result;
+
+pub struct PlaylistEntry {
+    pub duration_secs: u32,
+    pub title: String,
+    pub filename: String,
+}
+
+pub fn write_m3u8(path: &Path, entries: &[PlaylistEntry]) -> Result<()> {
+    let mut out = String::from("#EXTM3U\n");
+    for entry in entries {
+        writeln!(out, "#EXTINF:{},{}", entry.duration_secs, entry.title)?;
+        writeln!(out, "{}", entry.filename)?;
+    }
+    std::fs::write(path, out)?;
+    Ok(())
+}
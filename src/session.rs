@@ -0,0 +1,19 @@
+use anyhow::Result;
+use librespot::core::authentication::Credentials;
+use librespot::core::config::SessionConfig;
+use librespot::core::session::Session;
+
+/// Authenticates against Spotify using credentials from the environment
+/// and returns a ready-to-use session.
+pub async fn create_session() -> Result<Session> {
+    let username = std::env::var("SPOTIFY_USERNAME")
+        .map_err(|_| anyhow::anyhow!("SPOTIFY_USERNAME is not set"))?;
+    let password = std::env::var("SPOTIFY_PASSWORD")
+        .map_err(|_| anyhow::anyhow!("SPOTIFY_PASSWORD is not set"))?;
+
+    let session_config = SessionConfig::default();
+    let credentials = Credentials::with_password(username, password);
+    let session = Session::new(session_config, None);
+    session.connect(credentials, true).await?;
+    Ok(session)
+}
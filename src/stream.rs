@@ -0,0 +1,157 @@
+use std::io::Read;
+
+use anyhow::Result;
+use librespot::core::session::Session;
+use librespot::metadata::FileFormat;
+use librespot::playback::audio_backend::AudioFile;
+use tokio::sync::mpsc;
+
+use crate::download::{looks_rate_limited, Quality};
+use crate::track::Track;
+
+const CHANNEL_CAPACITY: usize = 16;
+const CHUNK_SIZE: usize = 1024 * 64;
+
+pub type StreamEventChannel = mpsc::Receiver<StreamEvent>;
+
+#[derive(Debug, Clone)]
+pub enum StreamError {
+    /// None of the `FileFormat`s in the requested quality's fallback chain
+    /// were available for this track.
+    NoSuitableFormat,
+    /// Spotify responded with an HTTP 429 / "too many requests" style
+    /// error. Distinct from `Io` so callers can drive the adaptive rate
+    /// limiter off it directly instead of a generic transient failure.
+    RateLimited,
+    Io(String),
+}
+
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// Emitted once, as soon as the fallback chain has settled on a
+    /// concrete `FileFormat`, so callers can report what quality was
+    /// actually obtained.
+    Resolved(FileFormat),
+    Write {
+        bytes: usize,
+        total: usize,
+        content: Vec<i32>,
+    },
+    Retry {
+        attempt: u32,
+        max_attempts: u32,
+    },
+    Finished,
+    Error(StreamError),
+}
+
+pub struct Stream {
+    session: Session,
+    quality: Quality,
+}
+
+impl Stream {
+    pub fn new(session: Session, quality: Quality) -> Self {
+        Stream { session, quality }
+    }
+
+    /// Streams `track`, walking the configured quality's fallback chain of
+    /// `FileFormat`s until one is available, rather than failing outright
+    /// when the highest-quality file id doesn't exist for this track.
+    pub async fn stream(&self, track: Track) -> Result<StreamEventChannel> {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let session = self.session.clone();
+        let formats = self.quality.fallback_chain();
+
+        tokio::spawn(async move {
+            for format in formats {
+                let file_id = match track.file_id_for_format(&session, format).await {
+                    Ok(Some(file_id)) => file_id,
+                    Ok(None) => continue,
+                    Err(e) if looks_rate_limited(&e.to_string()) => {
+                        let _ = tx.send(StreamEvent::Error(StreamError::RateLimited)).await;
+                        return;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error(StreamError::Io(e.to_string()))).await;
+                        return;
+                    }
+                };
+
+                let _ = tx.send(StreamEvent::Resolved(format)).await;
+                match stream_samples(&session, file_id, &tx).await {
+                    Ok(()) => {
+                        let _ = tx.send(StreamEvent::Finished).await;
+                        return;
+                    }
+                    Err(e) if looks_rate_limited(&e.to_string()) => {
+                        let _ = tx.send(StreamEvent::Error(StreamError::RateLimited)).await;
+                        return;
+                    }
+                    Err(e) => {
+                        // The format's file id exists, so this is a stream
+                        // failure, not an unavailable quality: falling back
+                        // here would append another format's samples to the
+                        // ones already sent.
+                        let _ = tx.send(StreamEvent::Error(StreamError::Io(e.to_string()))).await;
+                        return;
+                    }
+                }
+            }
+
+            let _ = tx.send(StreamEvent::Error(StreamError::NoSuitableFormat)).await;
+        });
+
+        Ok(rx)
+    }
+}
+
+/// Reads `file_id` in `CHUNK_SIZE`-sized windows, decoding each window into
+/// samples and emitting it as its own `StreamEvent::Write` as soon as it's
+/// read, so at most one chunk is held in memory at a time rather than
+/// buffering the whole track before handing it to the encoder.
+async fn stream_samples(
+    session: &Session,
+    file_id: librespot::core::file_id::FileId,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> Result<()> {
+    let mut file = AudioFile::open(session, file_id, CHUNK_SIZE)?;
+    let mut raw = vec![0u8; CHUNK_SIZE];
+    let mut carry = Vec::with_capacity(4);
+    let mut total_bytes = 0usize;
+
+    loop {
+        let read = file.read(&mut raw)?;
+        if read == 0 {
+            break;
+        }
+        total_bytes += read;
+
+        carry.extend_from_slice(&raw[..read]);
+        let whole_samples = carry.len() / 4;
+        let split_at = whole_samples * 4;
+        let samples: Vec<i32> = carry[..split_at]
+            .chunks_exact(4)
+            .map(|chunk| i32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        carry.drain(..split_at);
+
+        if samples.is_empty() {
+            continue;
+        }
+
+        if tx
+            .send(StreamEvent::Write {
+                bytes: total_bytes,
+                total: total_bytes,
+                content: samples,
+            })
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+use anyhow::Result;
+use librespot::core::file_id::FileId;
+use librespot::core::session::Session;
+use librespot::core::spotify_id::SpotifyId;
+use librespot::metadata::FileFormat;
+use librespot::metadata::Metadata;
+
+/// A single track queued for download, parsed from a URI or bare base62 id
+/// given on the command line.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub id: SpotifyId,
+}
+
+/// Parses each user-supplied Spotify URI (or bare base62 id) into one
+/// `Track`. Playlist and album URIs are not expanded into their tracks,
+/// and a string that is neither a URI nor a base62 id (such as an
+/// `open.spotify.com` URL) is an error.
+pub async fn get_tracks(uris: Vec<String>, _session: &Session) -> Result<Vec<Track>> {
+    uris.into_iter()
+        .map(|uri| {
+            SpotifyId::from_uri(&uri)
+                .or_else(|_| SpotifyId::from_base62(&uri))
+                .map(|id| Track { id })
+                .map_err(|_| anyhow::anyhow!("Could not parse Spotify URI: {}", uri))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackMetadata {
+    pub track_name: String,
+    pub artist_name: String,
+    pub duration_ms: u32,
+    pub raw_size_bytes: usize,
+    /// The `FileFormat` the quality fallback chain actually settled on,
+    /// filled in once streaming resolves it. `None` until then.
+    pub resolved_quality: Option<FileFormat>,
+}
+
+impl TrackMetadata {
+    pub fn approx_size(&self) -> usize {
+        self.raw_size_bytes
+    }
+
+    pub async fn tags(&self) -> Result<Tags> {
+        Ok(Tags {
+            title: self.track_name.clone(),
+            artist: self.artist_name.clone(),
+            album: String::new(),
+        })
+    }
+}
+
+impl fmt::Display for TrackMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.artist_name, self.track_name)
+    }
+}
+
+impl Track {
+    pub async fn metadata(&self, _session: &Session) -> Result<TrackMetadata> {
+        Ok(TrackMetadata {
+            track_name: self.id.to_base62()?,
+            artist_name: String::new(),
+            duration_ms: 0,
+            raw_size_bytes: 0,
+            resolved_quality: None,
+        })
+    }
+
+    /// Looks up the `FileId` Spotify has on file for this track in a given
+    /// `FileFormat`, or `None` if that format isn't available for it (e.g.
+    /// a track with no lossless encode, or no MP3 transcode).
+    pub async fn file_id_for_format(
+        &self,
+        session: &Session,
+        format: FileFormat,
+    ) -> Result<Option<FileId>> {
+        let track = librespot::metadata::Track::get(session, self.id).await?;
+        Ok(track.files.get(&format).copied())
+    }
+
+    /// The canonical `spotify:track:...` URI, used as the stable key for
+    /// manifest entries across runs.
+    pub fn uri(&self) -> Result<String> {
+        Ok(self.id.to_uri()?)
+    }
+}